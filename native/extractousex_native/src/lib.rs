@@ -1,6 +1,19 @@
-use rustler::{NifResult, Binary};
-use extractous::{Extractor, CharSet, PdfOcrStrategy, PdfParserConfig, OfficeParserConfig, TesseractOcrConfig};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::sync::Mutex;
+use rustler::{Encoder, Env, NifResult, OwnedBinary, Resource, ResourceArc, Term, Binary};
+use extractous::{Extractor, CharSet, PdfOcrStrategy, PdfParserConfig, OfficeParserConfig, TesseractOcrConfig, StreamReader};
 use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+mod atoms {
+    rustler::atoms! {
+        ok,
+        eof,
+        error,
+    }
+}
 
 // JSON configuration structures with general options at top level
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -12,6 +25,10 @@ struct ExtractorConfig {
     xml: Option<bool>,
     #[serde(default)]
     encoding: Option<String>,
+    // When set, also populate the legacy `Debug`-formatted metadata string
+    // alongside the structured metadata map, for callers mid-migration.
+    #[serde(default)]
+    include_legacy_metadata_string: Option<bool>,
 
     // Nested config groups
     #[serde(default)]
@@ -20,9 +37,11 @@ struct ExtractorConfig {
     office: OfficeSettings,
     #[serde(default)]
     ocr: OcrSettings,
+    #[serde(default)]
+    images: ImageSettings,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 struct PdfSettings {
     ocr_strategy: Option<String>,
     extract_annotation_text: Option<bool>,
@@ -45,7 +64,7 @@ struct OfficeSettings {
     extract_all_alternatives_from_msg: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 struct OcrSettings {
     language: Option<String>,
     timeout_seconds: Option<i32>,
@@ -53,16 +72,65 @@ struct OcrSettings {
     depth: Option<i32>,
     apply_rotation: Option<bool>,
     enable_image_preprocessing: Option<bool>,
+    // When true, the best-matching language among `languages` is picked from
+    // a quick classification pass and tried first, ahead of the other candidates.
+    #[serde(default)]
+    auto_detect: Option<bool>,
+    // Ordered candidate languages; joined with `+` as a Tesseract fallback
+    // chain, which `auto_detect` reorders so the detected language comes first.
+    #[serde(default)]
+    languages: Option<Vec<String>>,
+}
+
+// `target_dpi`/`grayscale` layer onto the Tesseract OCR config directly.
+// `max_dimension`/`threshold` are accepted for config compatibility but
+// rejected by `reject_unsupported_image_settings`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+struct ImageSettings {
+    grayscale: Option<bool>,
+    target_dpi: Option<u32>,
+    max_dimension: Option<u32>,
+    threshold: Option<u8>,
+}
+
+// extractous has no API to hand back decoded image bytes for `max_dimension`/
+// `threshold` to reshape, so reject them loudly instead of silently ignoring.
+fn reject_unsupported_image_settings(image_settings: &ImageSettings) -> NifResult<()> {
+    if image_settings.max_dimension.is_some() || image_settings.threshold.is_some() {
+        return Err(rustler::Error::Term(Box::new(
+            "images.max_dimension and images.threshold are not supported: extractous has no way to return decoded inline image bytes to reshape. Use images.target_dpi/images.grayscale, which apply directly to the OCR pass.".to_string(),
+        )));
+    }
+    Ok(())
 }
 
-fn configure_extractor_from_json(config_json: Option<String>) -> NifResult<Extractor> {
-    let config = if let Some(json_str) = config_json {
-        serde_json::from_str::<ExtractorConfig>(&json_str)
-            .map_err(|e| rustler::Error::Term(Box::new(format!("Invalid JSON configuration: {}", e))))?
+fn configure_extractor_from_config(source: Option<String>, format: Option<String>) -> NifResult<(Extractor, bool, ImageSettings, OcrSettings, PdfSettings)> {
+    let config = if let Some(raw) = source {
+        let format = format.as_deref().unwrap_or("json").to_lowercase();
+        match format.as_str() {
+            "json" => serde_json::from_str::<ExtractorConfig>(&raw)
+                .map_err(|e| rustler::Error::Term(Box::new(format!("Invalid JSON configuration: {}", e))))?,
+            "toml" => toml::from_str::<ExtractorConfig>(&raw)
+                .map_err(|e| rustler::Error::Term(Box::new(format!("Invalid TOML configuration: {}", e))))?,
+            "yaml" | "yml" => serde_yaml::from_str::<ExtractorConfig>(&raw)
+                .map_err(|e| rustler::Error::Term(Box::new(format!("Invalid YAML configuration: {}", e))))?,
+            other => {
+                return Err(rustler::Error::Term(Box::new(format!(
+                    "Unsupported configuration format: {}. Supported formats: json, toml, yaml",
+                    other
+                ))))
+            }
+        }
     } else {
         ExtractorConfig::default()
     };
 
+    let include_legacy_metadata_string = config.include_legacy_metadata_string.unwrap_or(false);
+    let image_settings = config.images.clone();
+    reject_unsupported_image_settings(&image_settings)?;
+    let ocr_settings = config.ocr.clone();
+    let pdf_settings = config.pdf.clone();
+
     let mut extractor = Extractor::new();
 
     // Apply top-level general settings
@@ -164,67 +232,724 @@ fn configure_extractor_from_json(config_json: Option<String>) -> NifResult<Extra
 
     extractor = extractor.set_office_config(office_config);
 
-    // Configure Tesseract OCR
+    extractor = extractor.set_ocr_config(build_ocr_config(&config.ocr, None));
+
+    Ok((extractor, include_legacy_metadata_string, image_settings, ocr_settings, pdf_settings))
+}
+
+// Builds a TesseractOcrConfig from every configured OCR setting.
+// `language_override` takes precedence over `settings.language`/`languages`,
+// used to reconfigure just the language after auto-detection without losing
+// the rest of the caller's OCR settings.
+fn build_ocr_config(settings: &OcrSettings, language_override: Option<&str>) -> TesseractOcrConfig {
     let mut ocr_config = TesseractOcrConfig::new();
 
-    if let Some(language) = config.ocr.language {
+    let language = language_override
+        .map(|language| language.to_string())
+        .or_else(|| resolve_ocr_language(settings));
+    if let Some(language) = language {
         ocr_config = ocr_config.set_language(&language);
     }
 
-    if let Some(timeout_seconds) = config.ocr.timeout_seconds {
+    if let Some(timeout_seconds) = settings.timeout_seconds {
         ocr_config = ocr_config.set_timeout_seconds(timeout_seconds);
     }
 
-    if let Some(density) = config.ocr.density {
+    if let Some(density) = settings.density {
         ocr_config = ocr_config.set_density(density);
     }
 
-    if let Some(depth) = config.ocr.depth {
+    if let Some(depth) = settings.depth {
         ocr_config = ocr_config.set_depth(depth);
     }
 
-    if let Some(apply_rotation) = config.ocr.apply_rotation {
+    if let Some(apply_rotation) = settings.apply_rotation {
         ocr_config = ocr_config.set_apply_rotation(apply_rotation);
     }
 
-    if let Some(enable_image_preprocessing) = config.ocr.enable_image_preprocessing {
+    if let Some(enable_image_preprocessing) = settings.enable_image_preprocessing {
         ocr_config = ocr_config.set_enable_image_preprocessing(enable_image_preprocessing);
     }
 
-    extractor = extractor.set_ocr_config(ocr_config);
+    ocr_config
+}
 
-    Ok(extractor)
+// Joins the ordered `languages` list into a Tesseract `+`-separated fallback
+// chain, falling back to the single `language` field for older configs.
+fn resolve_ocr_language(settings: &OcrSettings) -> Option<String> {
+    match &settings.languages {
+        Some(languages) if !languages.is_empty() => Some(languages.join("+")),
+        _ => settings.language.clone(),
+    }
 }
 
-#[rustler::nif(schedule = "DirtyCpu")]
-fn extract(file_path: String, config_json: Option<String>) -> NifResult<(String, String)> {
-    let extractor = configure_extractor_from_json(config_json)?;
+// Tesseract language codes for the scripts `whatlang` can reliably tell apart.
+fn tesseract_code_for(lang: whatlang::Lang) -> Option<&'static str> {
+    use whatlang::Lang;
+
+    Some(match lang {
+        Lang::Eng => "eng",
+        Lang::Fra => "fra",
+        Lang::Deu => "deu",
+        Lang::Spa => "spa",
+        Lang::Ita => "ita",
+        Lang::Por => "por",
+        Lang::Rus => "rus",
+        Lang::Cmn => "chi_sim",
+        Lang::Jpn => "jpn",
+        Lang::Kor => "kor",
+        Lang::Ara => "ara",
+        Lang::Nld => "nld",
+        Lang::Pol => "pol",
+        _ => return None,
+    })
+}
 
-    match extractor.extract_file_to_string(&file_path) {
-        Ok((content, metadata)) => Ok((content, format!("{:?}", metadata))),
-        Err(e) => Err(rustler::Error::Term(Box::new(format!("Extraction failed: {}", e)))),
+// Classifies a text sample and returns a `+`-joined Tesseract fallback chain
+// with the best-matching candidate language moved to the front.
+fn detect_ocr_language(settings: &OcrSettings, sample: &str) -> Option<String> {
+    let languages = settings.languages.as_ref()?;
+    if languages.is_empty() {
+        return None;
     }
+
+    let detected = whatlang::detect(sample).and_then(|info| tesseract_code_for(info.lang()));
+
+    let primary = match detected {
+        Some(code) if languages.iter().any(|language| language == code) => code.to_string(),
+        _ => languages[0].clone(),
+    };
+
+    // The detected (or default) language goes first so Tesseract tries it
+    // before the rest, but every other configured candidate still rides
+    // along in the `+`-joined chain — otherwise a mixed-language document
+    // loses OCR coverage for every page that isn't in the detected language.
+    let mut chain = vec![primary.clone()];
+    chain.extend(languages.iter().filter(|language| **language != primary).cloned());
+
+    Some(chain.join("+"))
+}
+
+// Tika metadata keys are multivalued, so each key maps to a list of strings
+// rather than a single value.
+fn metadata_to_term<'a>(env: Env<'a>, metadata: &extractous::Metadata) -> NifResult<Term<'a>> {
+    metadata_to_term_with_ocr_language(env, metadata, None)
+}
+
+fn metadata_to_term_with_ocr_language<'a>(
+    env: Env<'a>,
+    metadata: &extractous::Metadata,
+    ocr_language: Option<&str>,
+) -> NifResult<Term<'a>> {
+    let mut pairs: Vec<(String, Vec<String>)> = metadata
+        .keys()
+        .map(|key| {
+            let values = metadata.get(key).cloned().unwrap_or_default();
+            (key.clone(), values)
+        })
+        .collect();
+
+    if let Some(language) = ocr_language {
+        pairs.push(("X-OCR-Language".to_string(), vec![language.to_string()]));
+    }
+
+    Term::map_from_pairs(env, &pairs)
+        .map_err(|_| rustler::Error::Term(Box::new("Failed to encode metadata map")))
+}
+
+// Builds the NIF return value: a 2-tuple `{content, metadata}` by default, or
+// a 3-tuple `{content, metadata, legacy_metadata_string}` when the caller set
+// `include_legacy_metadata_string`, so callers who never opted into the
+// legacy string aren't handed a `{content, metadata} = ...` match failure.
+fn encode_extract_result<'a>(
+    env: Env<'a>,
+    content: String,
+    metadata: &extractous::Metadata,
+    ocr_language: Option<&str>,
+    include_legacy_metadata_string: bool,
+) -> NifResult<Term<'a>> {
+    let metadata_term = metadata_to_term_with_ocr_language(env, metadata, ocr_language)?;
+    let content_term = content.encode(env);
+    let terms = if include_legacy_metadata_string {
+        vec![content_term, metadata_term, format!("{:?}", metadata).encode(env)]
+    } else {
+        vec![content_term, metadata_term]
+    };
+    Ok(rustler::types::tuple::make_tuple(env, &terms))
+}
+
+// Returns a copy of the extractor with Tesseract reconfigured for the
+// `language` chain picked by OCR auto-detection, for the second extraction
+// pass. Carries every other OCR setting forward unchanged, including the
+// `images` preprocessing knobs the sample pass was built with, so only the
+// language is overridden.
+fn reconfigure_ocr_language(extractor: &Extractor, ocr_settings: &OcrSettings, image_settings: &ImageSettings, language: &str) -> Extractor {
+    extractor
+        .clone()
+        .set_ocr_config(build_ocr_config_with_images(ocr_settings, image_settings, Some(language)))
+}
+
+// Language auto-detection only needs a small text sample, not the whole
+// document, so this caps the detection pass at a fixed length to keep it
+// cheap; the cap replaces, rather than narrows, the caller's max length.
+const OCR_LANGUAGE_SAMPLE_MAX_LENGTH: i32 = 4096;
+
+fn ocr_language_sample_extractor(extractor: &Extractor) -> Extractor {
+    extractor.clone().set_extract_string_max_length(OCR_LANGUAGE_SAMPLE_MAX_LENGTH)
+}
+
+// `auto_detect` only does anything once `languages` is populated.
+fn auto_detect_enabled(settings: &OcrSettings) -> bool {
+    settings.auto_detect.unwrap_or(false) && settings.languages.as_ref().is_some_and(|languages| !languages.is_empty())
+}
+
+// `NO_OCR` is the only PDF OCR strategy that guarantees OCR never runs.
+fn pdf_ocr_strategy_may_run_ocr(pdf: &PdfSettings) -> bool {
+    !matches!(pdf.ocr_strategy.as_deref(), Some(strategy) if strategy.eq_ignore_ascii_case("NO_OCR"))
+}
+
+// The sample pass is only worth paying for when OCR could actually run.
+fn should_detect_ocr_language(ocr: &OcrSettings, pdf: &PdfSettings) -> bool {
+    auto_detect_enabled(ocr) && pdf_ocr_strategy_may_run_ocr(pdf)
+}
+
+// Only `extract`/`extract_bytes`/`extract_url` have a natural place for the
+// sample-then-reconfigure auto-detect pass; streaming and archive extraction
+// don't, so auto_detect is rejected up front there instead of silently
+// falling back to the static language chain.
+fn reject_ocr_auto_detect(ocr_settings: &OcrSettings) -> NifResult<()> {
+    if auto_detect_enabled(ocr_settings) {
+        return Err(rustler::Error::Term(Box::new(
+            "ocr.auto_detect is not supported here; use extract/extract_bytes/extract_url, or set ocr.language/ocr.languages explicitly".to_string(),
+        )));
+    }
+    Ok(())
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
-fn extract_bytes(buffer: Binary, config_json: Option<String>) -> NifResult<(String, String)> {
-    let extractor = configure_extractor_from_json(config_json)?;
+fn extract<'a>(env: Env<'a>, file_path: String, config: Option<String>, config_format: Option<String>) -> NifResult<Term<'a>> {
+    let (extractor, include_legacy_metadata_string, image_settings, ocr_settings, pdf_settings) = configure_extractor_from_config(config, config_format)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
+
+    let (content, metadata, ocr_language) = if should_detect_ocr_language(&ocr_settings, &pdf_settings) {
+        let (sample_content, _) = ocr_language_sample_extractor(&extractor)
+            .extract_file_to_string(&file_path)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction failed: {}", e))))?;
+        let language = detect_ocr_language(&ocr_settings, &sample_content);
+        let active_extractor = match &language {
+            Some(language) => reconfigure_ocr_language(&extractor, &ocr_settings, &image_settings, language),
+            None => extractor.clone(),
+        };
+        let (content, metadata) = active_extractor
+            .extract_file_to_string(&file_path)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction failed: {}", e))))?;
+        (content, metadata, language)
+    } else {
+        let (content, metadata) = extractor
+            .extract_file_to_string(&file_path)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction failed: {}", e))))?;
+        (content, metadata, resolve_ocr_language(&ocr_settings))
+    };
+
+    encode_extract_result(env, content, &metadata, ocr_language.as_deref(), include_legacy_metadata_string)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_bytes<'a>(env: Env<'a>, buffer: Binary, config: Option<String>, config_format: Option<String>) -> NifResult<Term<'a>> {
+    let (extractor, include_legacy_metadata_string, image_settings, ocr_settings, pdf_settings) = configure_extractor_from_config(config, config_format)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
     let bytes = buffer.as_slice();
 
-    match extractor.extract_bytes_to_string(bytes) {
-        Ok((content, metadata)) => Ok((content, format!("{:?}", metadata))),
-        Err(e) => Err(rustler::Error::Term(Box::new(format!("Extraction from bytes failed: {}", e)))),
+    let (content, metadata, ocr_language) = if should_detect_ocr_language(&ocr_settings, &pdf_settings) {
+        let (sample_content, _) = ocr_language_sample_extractor(&extractor)
+            .extract_bytes_to_string(bytes)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from bytes failed: {}", e))))?;
+        let language = detect_ocr_language(&ocr_settings, &sample_content);
+        let active_extractor = match &language {
+            Some(language) => reconfigure_ocr_language(&extractor, &ocr_settings, &image_settings, language),
+            None => extractor.clone(),
+        };
+        let (content, metadata) = active_extractor
+            .extract_bytes_to_string(bytes)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from bytes failed: {}", e))))?;
+        (content, metadata, language)
+    } else {
+        let (content, metadata) = extractor
+            .extract_bytes_to_string(bytes)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from bytes failed: {}", e))))?;
+        (content, metadata, resolve_ocr_language(&ocr_settings))
+    };
+
+    encode_extract_result(env, content, &metadata, ocr_language.as_deref(), include_legacy_metadata_string)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_url<'a>(env: Env<'a>, url: String, config: Option<String>, config_format: Option<String>) -> NifResult<Term<'a>> {
+    let (extractor, include_legacy_metadata_string, image_settings, ocr_settings, pdf_settings) = configure_extractor_from_config(config, config_format)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
+
+    let (content, metadata, ocr_language) = if should_detect_ocr_language(&ocr_settings, &pdf_settings) {
+        let (sample_content, _) = ocr_language_sample_extractor(&extractor)
+            .extract_url_to_string(&url)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from URL failed: {}", e))))?;
+        let language = detect_ocr_language(&ocr_settings, &sample_content);
+        let active_extractor = match &language {
+            Some(language) => reconfigure_ocr_language(&extractor, &ocr_settings, &image_settings, language),
+            None => extractor.clone(),
+        };
+        let (content, metadata) = active_extractor
+            .extract_url_to_string(&url)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from URL failed: {}", e))))?;
+        (content, metadata, language)
+    } else {
+        let (content, metadata) = extractor
+            .extract_url_to_string(&url)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Extraction from URL failed: {}", e))))?;
+        (content, metadata, resolve_ocr_language(&ocr_settings))
+    };
+
+    encode_extract_result(env, content, &metadata, ocr_language.as_deref(), include_legacy_metadata_string)
+}
+
+// Holds the open StreamReader for a chunked extraction so it can be read
+// incrementally from Elixir instead of materializing the whole document.
+struct StreamResource(Mutex<StreamReader>);
+
+#[rustler::resource_impl]
+impl Resource for StreamResource {}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_stream<'a>(env: Env<'a>, file_path: String, config: Option<String>, config_format: Option<String>) -> NifResult<(rustler::Atom, ResourceArc<StreamResource>, Term<'a>)> {
+    let (extractor, _, image_settings, ocr_settings, _) = configure_extractor_from_config(config, config_format)?;
+    reject_ocr_auto_detect(&ocr_settings)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
+
+    match extractor.extract_file(&file_path) {
+        Ok((reader, metadata)) => {
+            let resource = ResourceArc::new(StreamResource(Mutex::new(reader)));
+            Ok((atoms::ok(), resource, metadata_to_term(env, &metadata)?))
+        }
+        Err(e) => Err(rustler::Error::Term(Box::new(format!("Failed to open stream: {}", e)))),
+    }
+}
+
+// Caps a single `read_chunk` call's allocation, since Rust's global allocator
+// aborts the whole process on failure rather than returning an error.
+const READ_CHUNK_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn read_chunk<'a>(env: Env<'a>, resource: ResourceArc<StreamResource>, max_bytes: usize) -> NifResult<Term<'a>> {
+    if max_bytes > READ_CHUNK_MAX_BYTES {
+        return Err(rustler::Error::Term(Box::new(format!(
+            "max_bytes {} exceeds the maximum of {} bytes per read_chunk call",
+            max_bytes, READ_CHUNK_MAX_BYTES
+        ))));
+    }
+
+    if max_bytes == 0 {
+        let owned_binary = OwnedBinary::new(0)
+            .ok_or_else(|| rustler::Error::Term(Box::new("Failed to allocate binary")))?;
+        return Ok((atoms::ok(), Binary::from_owned(owned_binary, env)).encode(env));
+    }
+
+    let mut reader = resource
+        .0
+        .lock()
+        .map_err(|_| rustler::Error::Term(Box::new("Stream lock poisoned")))?;
+
+    let mut buf = vec![0u8; max_bytes];
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) => return Err(rustler::Error::Term(Box::new(format!("Stream read failed: {}", e)))),
+        }
+    }
+
+    if read == 0 {
+        return Ok(atoms::eof().encode(env));
+    }
+
+    let mut owned_binary = OwnedBinary::new(read)
+        .ok_or_else(|| rustler::Error::Term(Box::new("Failed to allocate binary")))?;
+    owned_binary.as_mut_slice().copy_from_slice(&buf[..read]);
+
+    Ok((atoms::ok(), Binary::from_owned(owned_binary, env)).encode(env))
+}
+
+// Walks a ZIP archive or a directory tree and extracts every file it
+// contains. A single entry failing extraction is reported per-entry as
+// `{:error, reason}` rather than aborting the rest of the archive.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_archive<'a>(env: Env<'a>, path: String, config: Option<String>, config_format: Option<String>) -> NifResult<Vec<(String, Term<'a>)>> {
+    let (extractor, _, image_settings, ocr_settings, _) = configure_extractor_from_config(config, config_format)?;
+    reject_ocr_auto_detect(&ocr_settings)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
+
+    if fs::metadata(&path)
+        .map_err(|e| rustler::Error::Term(Box::new(format!("Failed to read archive: {}", e))))?
+        .is_dir()
+    {
+        extract_directory_entries(env, &path, &extractor)
+    } else {
+        let file = fs::File::open(&path)
+            .map_err(|e| rustler::Error::Term(Box::new(format!("Failed to read archive: {}", e))))?;
+        extract_zip_entries(env, std::io::BufReader::new(file), &extractor)
     }
 }
 
 #[rustler::nif(schedule = "DirtyCpu")]
-fn extract_url(url: String, config_json: Option<String>) -> NifResult<(String, String)> {
-    let extractor = configure_extractor_from_json(config_json)?;
+fn extract_archive_bytes<'a>(env: Env<'a>, buffer: Binary, config: Option<String>, config_format: Option<String>) -> NifResult<Vec<(String, Term<'a>)>> {
+    let (extractor, _, image_settings, ocr_settings, _) = configure_extractor_from_config(config, config_format)?;
+    reject_ocr_auto_detect(&ocr_settings)?;
+    let extractor = apply_image_preprocessing_to_ocr(&extractor, &ocr_settings, &image_settings);
+
+    extract_zip_entries(env, Cursor::new(buffer.as_slice()), &extractor)
+}
+
+// Encodes one archive entry's outcome as `{:ok, content, metadata}` or
+// `{:error, reason}` so a failed entry can be reported without discarding
+// every entry that already extracted successfully.
+fn encode_entry_result<'a>(env: Env<'a>, outcome: Result<(String, extractous::Metadata), String>) -> NifResult<Term<'a>> {
+    match outcome {
+        Ok((content, metadata)) => {
+            let metadata_term = metadata_to_term(env, &metadata)?;
+            Ok((atoms::ok(), content, metadata_term).encode(env))
+        }
+        Err(reason) => Ok((atoms::error(), reason).encode(env)),
+    }
+}
+
+// Walks the directory one file at a time, extracting as it goes, so a large
+// tree is never fully read into memory before extraction starts.
+fn extract_directory_entries<'a>(env: Env<'a>, path: &str, extractor: &Extractor) -> NifResult<Vec<(String, Term<'a>)>> {
+    let mut results = Vec::new();
+    for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let entry_path = entry.path().display().to_string();
+        let outcome = fs::read(entry.path())
+            .map_err(|e| format!("Failed to read {}: {}", entry_path, e))
+            .and_then(|bytes| {
+                if sniff_mime(&bytes).is_none() {
+                    return Ok(None);
+                }
+                extractor
+                    .extract_bytes_to_string(&bytes)
+                    .map(Some)
+                    .map_err(|e| format!("Failed to extract {}: {}", entry_path, e))
+            });
+
+        match outcome {
+            Ok(None) => continue,
+            Ok(Some(extracted)) => results.push((entry_path, encode_entry_result(env, Ok(extracted))?)),
+            Err(reason) => results.push((entry_path, encode_entry_result(env, Err(reason))?)),
+        }
+    }
+
+    Ok(results)
+}
+
+// extractous lacks its own archive support, so entries are extracted one at a
+// time straight off the zip crate's deflate reader. Takes any `Read + Seek` so
+// the path-based caller can hand in a `BufReader<File>`.
+// A declared entry size in the zip header can't be trusted (classic zip
+// bomb), so this bounds the actual decompressed bytes read per entry.
+const ARCHIVE_ENTRY_MAX_DECOMPRESSED_BYTES: u64 = 256 * 1024 * 1024;
+
+// Bounds the sum of every entry's decompressed size across one archive call,
+// so many entries each under the per-entry cap can't add up to unbounded total.
+const ARCHIVE_TOTAL_MAX_DECOMPRESSED_BYTES: u64 = 10 * ARCHIVE_ENTRY_MAX_DECOMPRESSED_BYTES;
+
+fn extract_zip_entries<'a, R: Read + std::io::Seek>(env: Env<'a>, reader: R, extractor: &Extractor) -> NifResult<Vec<(String, Term<'a>)>> {
+    let mut archive = ZipArchive::new(reader)
+        .map_err(|e| rustler::Error::Term(Box::new(format!("Failed to read archive: {}", e))))?;
+
+    let mut results = Vec::with_capacity(archive.len());
+    let mut total_decompressed_bytes: u64 = 0;
+    for i in 0..archive.len() {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(e) => {
+                results.push((format!("entry {}", i), encode_entry_result(env, Err(format!("Failed to read archive entry {}: {}", i, e)))?));
+                continue;
+            }
+        };
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let entry_path = file.name().to_string();
+        let mut bytes = Vec::new();
+        let outcome = (&mut file)
+            .take(ARCHIVE_ENTRY_MAX_DECOMPRESSED_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Failed to read {}: {}", entry_path, e))
+            .and_then(|_| {
+                if bytes.len() as u64 > ARCHIVE_ENTRY_MAX_DECOMPRESSED_BYTES {
+                    return Err(format!(
+                        "Entry {} exceeds the maximum decompressed size of {} bytes",
+                        entry_path, ARCHIVE_ENTRY_MAX_DECOMPRESSED_BYTES
+                    ));
+                }
+                total_decompressed_bytes += bytes.len() as u64;
+                if total_decompressed_bytes > ARCHIVE_TOTAL_MAX_DECOMPRESSED_BYTES {
+                    return Err(format!(
+                        "Archive exceeds the maximum total decompressed size of {} bytes at entry {}",
+                        ARCHIVE_TOTAL_MAX_DECOMPRESSED_BYTES, entry_path
+                    ));
+                }
+                if sniff_mime(&bytes).is_none() {
+                    return Ok(None);
+                }
+                extractor
+                    .extract_bytes_to_string(&bytes)
+                    .map(Some)
+                    .map_err(|e| format!("Failed to extract {}: {}", entry_path, e))
+            });
+
+        match outcome {
+            Ok(None) => continue,
+            Ok(Some(extracted)) => results.push((entry_path, encode_entry_result(env, Ok(extracted))?)),
+            Err(reason) => results.push((entry_path, encode_entry_result(env, Err(reason))?)),
+        }
+
+        if total_decompressed_bytes > ARCHIVE_TOTAL_MAX_DECOMPRESSED_BYTES {
+            break;
+        }
+    }
+
+    Ok(results)
+}
 
-    match extractor.extract_url_to_string(&url) {
-        Ok((content, metadata)) => Ok((content, format!("{:?}", metadata))),
-        Err(e) => Err(rustler::Error::Term(Box::new(format!("Extraction from URL failed: {}", e)))),
+// One table of image signature/mime, so the archive sniffer below
+// knows exactly which image types are recognized.
+struct ImageKind {
+    signature: &'static [u8],
+    mime: &'static str,
+}
+
+const IMAGE_KINDS: &[ImageKind] = &[
+    ImageKind { signature: b"\x89PNG", mime: "image/png" },
+    ImageKind { signature: b"\xFF\xD8\xFF", mime: "image/jpeg" },
+    ImageKind { signature: b"GIF8", mime: "image/gif" },
+    ImageKind { signature: b"II*\x00", mime: "image/tiff" },
+    ImageKind { signature: b"MM\x00*", mime: "image/tiff" },
+    ImageKind { signature: b"BM", mime: "image/bmp" },
+];
+
+// Sniffs an archive entry's MIME type from its leading bytes so unsupported
+// file types can be skipped up front, while genuine extraction failures on a
+// recognized type are surfaced instead of silently dropped.
+fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"PK\x03\x04") {
+        Some("application/zip")
+    } else if bytes.starts_with(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+        Some("application/x-ole-storage")
+    } else if bytes.starts_with(b"{\\rtf") {
+        Some("application/rtf")
+    } else if let Some(kind) = IMAGE_KINDS.iter().find(|kind| bytes.starts_with(kind.signature)) {
+        Some(kind.mime)
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Some("text/plain")
+    } else {
+        None
     }
 }
 
-rustler::init!("Elixir.ExtractousEx.Native");
\ No newline at end of file
+// Layers `target_dpi`/`grayscale` from the image preprocessing settings onto
+// a `build_ocr_config` result, so the OCR'd `content` itself reflects those
+// two knobs. Shared by `apply_image_preprocessing_to_ocr` and
+// `reconfigure_ocr_language` so a language reconfigure after auto-detect
+// doesn't drop the preprocessing the sample pass was built with.
+fn build_ocr_config_with_images(ocr_settings: &OcrSettings, image_settings: &ImageSettings, language_override: Option<&str>) -> TesseractOcrConfig {
+    let mut ocr_config = build_ocr_config(ocr_settings, language_override);
+
+    if let Some(target_dpi) = image_settings.target_dpi {
+        ocr_config = ocr_config.set_density(target_dpi as i32);
+    }
+
+    if image_settings.grayscale.unwrap_or(false) {
+        ocr_config = ocr_config.set_enable_image_preprocessing(true);
+    }
+
+    ocr_config
+}
+
+fn apply_image_preprocessing_to_ocr(extractor: &Extractor, ocr_settings: &OcrSettings, image_settings: &ImageSettings) -> Extractor {
+    if image_settings.target_dpi.is_none() && !image_settings.grayscale.unwrap_or(false) {
+        return extractor.clone();
+    }
+
+    extractor.clone().set_ocr_config(build_ocr_config_with_images(ocr_settings, image_settings, None))
+}
+
+rustler::init!("Elixir.ExtractousEx.Native");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_ocr_language_joins_candidate_list() {
+        let settings = OcrSettings {
+            languages: Some(vec!["eng".to_string(), "fra".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(resolve_ocr_language(&settings), Some("eng+fra".to_string()));
+    }
+
+    #[test]
+    fn resolve_ocr_language_falls_back_to_single_language() {
+        let settings = OcrSettings {
+            language: Some("deu".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(resolve_ocr_language(&settings), Some("deu".to_string()));
+    }
+
+    #[test]
+    fn resolve_ocr_language_prefers_languages_over_language() {
+        let settings = OcrSettings {
+            language: Some("deu".to_string()),
+            languages: Some(vec!["eng".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(resolve_ocr_language(&settings), Some("eng".to_string()));
+    }
+
+    #[test]
+    fn detect_ocr_language_puts_detected_candidate_first_but_keeps_the_rest() {
+        let settings = OcrSettings {
+            languages: Some(vec!["fra".to_string(), "eng".to_string()]),
+            ..Default::default()
+        };
+        let sample = "This is a reasonably long sample of English text used for detection.";
+        assert_eq!(detect_ocr_language(&settings, sample), Some("eng+fra".to_string()));
+    }
+
+    #[test]
+    fn detect_ocr_language_falls_back_to_first_candidate_when_no_match() {
+        let settings = OcrSettings {
+            languages: Some(vec!["chi_sim".to_string(), "jpn".to_string()]),
+            ..Default::default()
+        };
+        let sample = "This is a reasonably long sample of English text used for detection.";
+        assert_eq!(detect_ocr_language(&settings, sample), Some("chi_sim+jpn".to_string()));
+    }
+
+    #[test]
+    fn tesseract_code_for_returns_none_for_unmapped_language() {
+        assert_eq!(tesseract_code_for(whatlang::Lang::Hin), None);
+    }
+
+    #[test]
+    fn detect_ocr_language_returns_none_without_candidates() {
+        let settings = OcrSettings::default();
+        assert_eq!(detect_ocr_language(&settings, "some text"), None);
+    }
+
+    #[test]
+    fn sniff_mime_identifies_common_formats() {
+        assert_eq!(sniff_mime(b"%PDF-1.7 rest of file"), Some("application/pdf"));
+        assert_eq!(sniff_mime(b"PK\x03\x04 rest of file"), Some("application/zip"));
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n rest"), Some("image/png"));
+        assert_eq!(sniff_mime(b"\xFF\xD8\xFF rest of jpeg"), Some("image/jpeg"));
+        assert_eq!(sniff_mime(b"II*\x00 rest of tiff"), Some("image/tiff"));
+        assert_eq!(sniff_mime(b"MM\x00* rest of tiff"), Some("image/tiff"));
+        assert_eq!(sniff_mime(b"BM rest of bmp"), Some("image/bmp"));
+        assert_eq!(sniff_mime(b"plain ascii text"), Some("text/plain"));
+        assert_eq!(sniff_mime(&[0xFF, 0x00, 0x10, 0x20, 0xDE, 0xAD]), None);
+    }
+
+    #[test]
+    fn reject_unsupported_image_settings_errors_only_when_set() {
+        assert!(reject_unsupported_image_settings(&ImageSettings::default()).is_ok());
+
+        let with_max_dimension = ImageSettings {
+            max_dimension: Some(1024),
+            ..Default::default()
+        };
+        assert!(reject_unsupported_image_settings(&with_max_dimension).is_err());
+
+        let with_threshold = ImageSettings {
+            threshold: Some(128),
+            ..Default::default()
+        };
+        assert!(reject_unsupported_image_settings(&with_threshold).is_err());
+    }
+
+    #[test]
+    fn configure_extractor_from_config_rejects_unknown_format() {
+        let result = configure_extractor_from_config(Some("{}".to_string()), Some("xml".to_string()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn auto_detect_enabled_requires_non_empty_languages() {
+        let without_languages = OcrSettings {
+            auto_detect: Some(true),
+            ..Default::default()
+        };
+        assert!(!auto_detect_enabled(&without_languages));
+
+        let with_languages = OcrSettings {
+            auto_detect: Some(true),
+            languages: Some(vec!["eng".to_string()]),
+            ..Default::default()
+        };
+        assert!(auto_detect_enabled(&with_languages));
+    }
+
+    #[test]
+    fn pdf_ocr_strategy_may_run_ocr_rules_out_no_ocr_only() {
+        let no_ocr = PdfSettings {
+            ocr_strategy: Some("no_ocr".to_string()),
+            ..Default::default()
+        };
+        assert!(!pdf_ocr_strategy_may_run_ocr(&no_ocr));
+
+        let auto = PdfSettings {
+            ocr_strategy: Some("AUTO".to_string()),
+            ..Default::default()
+        };
+        assert!(pdf_ocr_strategy_may_run_ocr(&auto));
+
+        assert!(pdf_ocr_strategy_may_run_ocr(&PdfSettings::default()));
+    }
+
+    #[test]
+    fn should_detect_ocr_language_skips_sample_pass_when_ocr_cannot_run() {
+        let ocr = OcrSettings {
+            auto_detect: Some(true),
+            languages: Some(vec!["eng".to_string(), "fra".to_string()]),
+            ..Default::default()
+        };
+        let no_ocr_pdf = PdfSettings {
+            ocr_strategy: Some("NO_OCR".to_string()),
+            ..Default::default()
+        };
+        assert!(!should_detect_ocr_language(&ocr, &no_ocr_pdf));
+        assert!(should_detect_ocr_language(&ocr, &PdfSettings::default()));
+    }
+
+    #[test]
+    fn reject_ocr_auto_detect_errors_only_when_auto_detect_is_active() {
+        let disabled = OcrSettings::default();
+        assert!(reject_ocr_auto_detect(&disabled).is_ok());
+
+        let enabled = OcrSettings {
+            auto_detect: Some(true),
+            languages: Some(vec!["eng".to_string(), "fra".to_string()]),
+            ..Default::default()
+        };
+        assert!(reject_ocr_auto_detect(&enabled).is_err());
+    }
+}
\ No newline at end of file